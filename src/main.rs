@@ -2,11 +2,14 @@
 
 extern crate minifb;
 
-use minifb::{Key, Window, WindowOptions};
-use std::{env, io};
+use minifb::{Key, KeyRepeat, Window, WindowOptions};
+use std::{env, fs, io};
 use std::io::prelude::*;
 use std::io::{stdin, stdout, Read, Write};
 use std::fs::File;
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 use rand::Rng;
 
 // sound
@@ -18,6 +21,9 @@ const PIXEL_HEIGHT: usize = 32;
 const FOREGROUND_COLOR: u32 = 0xFFFFFFFF;
 const BACKGROUND_COLOR: u32 = 0x00000000;
 const STACK_SIZE: usize = 16;
+const DEFAULT_INSTRUCTIONS_PER_SECOND: u32 = 600;
+const TIMER_HZ: u32 = 60;
+const PC_HISTORY_SIZE: usize = 64;
 
 const CHIP8_FONTSET: [u8; 80] =
 [
@@ -39,11 +45,199 @@ const CHIP8_FONTSET: [u8; 80] =
     0xF0, 0x80, 0xF0, 0x80, 0x80  //F
 ];
 
-fn pause() {
-    let mut stdout = stdout();
-    stdout.write(b"Press Enter to continue...").unwrap();
-    stdout.flush().unwrap();
-    stdin().read(&mut [0]).unwrap();
+// Decode a single opcode into a human readable mnemonic, e.g. `DRW V0,V1,5`.
+// Shared by the debugger's `disasm` command and the crash trace dump.
+fn disassemble(opcode: u16) -> String {
+    let x = (opcode >> 8) & 0x000F;
+    let y = (opcode >> 4) & 0x000F;
+    let nnn = opcode & 0x0FFF;
+    let kk = opcode & 0x00FF;
+    let n = opcode & 0x000F;
+    match opcode & 0xF000 {
+        0x0000 => match opcode {
+            0x00E0 => "CLS".to_string(),
+            0x00EE => "RET".to_string(),
+            _ => format!("SYS {:#05x}", nnn),
+        },
+        0x1000 => format!("JP {:#05x}", nnn),
+        0x2000 => format!("CALL {:#05x}", nnn),
+        0x3000 => format!("SE V{:X},{:#04x}", x, kk),
+        0x4000 => format!("SNE V{:X},{:#04x}", x, kk),
+        0x5000 => format!("SE V{:X},V{:X}", x, y),
+        0x6000 => format!("LD V{:X},{:#04x}", x, kk),
+        0x7000 => format!("ADD V{:X},{:#04x}", x, kk),
+        0x8000 => match n {
+            0x0 => format!("LD V{:X},V{:X}", x, y),
+            0x1 => format!("OR V{:X},V{:X}", x, y),
+            0x2 => format!("AND V{:X},V{:X}", x, y),
+            0x3 => format!("XOR V{:X},V{:X}", x, y),
+            0x4 => format!("ADD V{:X},V{:X}", x, y),
+            0x5 => format!("SUB V{:X},V{:X}", x, y),
+            0x6 => format!("SHR V{:X}", x),
+            0x7 => format!("SUBN V{:X},V{:X}", x, y),
+            0xE => format!("SHL V{:X}", x),
+            _ => format!("DW {:#06x}", opcode),
+        },
+        0x9000 => format!("SNE V{:X},V{:X}", x, y),
+        0xA000 => format!("LD I,{:#05x}", nnn),
+        0xB000 => format!("JP V0,{:#05x}", nnn),
+        0xC000 => format!("RND V{:X},{:#04x}", x, kk),
+        0xD000 => format!("DRW V{:X},V{:X},{}", x, y, n),
+        0xE000 => match kk {
+            0x9E => format!("SKP V{:X}", x),
+            0xA1 => format!("SKNP V{:X}", x),
+            _ => format!("DW {:#06x}", opcode),
+        },
+        0xF000 => match kk {
+            0x07 => format!("LD V{:X},DT", x),
+            0x0A => format!("LD V{:X},K", x),
+            0x15 => format!("LD DT,V{:X}", x),
+            0x18 => format!("LD ST,V{:X}", x),
+            0x1E => format!("ADD I,V{:X}", x),
+            0x29 => format!("LD F,V{:X}", x),
+            0x33 => format!("LD B,V{:X}", x),
+            0x55 => format!("LD [I],V{:X}", x),
+            0x65 => format!("LD V{:X},[I]", x),
+            _ => format!("DW {:#06x}", opcode),
+        },
+        _ => format!("DW {:#06x}", opcode),
+    }
+}
+
+// A single 8 bit counter that ticks down at a fixed 60 Hz, decoupled from
+// however fast the interpreter happens to run. Both the delay and sound
+// timers are just instances of this.
+struct Timer {
+    value: u8,
+}
+
+impl Timer {
+    fn new() -> Timer {
+        Timer { value: 0 }
+    }
+
+    fn set(&mut self, value: u8) {
+        self.value = value;
+    }
+
+    fn get(&self) -> u8 {
+        self.value
+    }
+
+    fn tick(&mut self) {
+        self.value = self.value.saturating_sub(1);
+    }
+}
+
+// How `Fx55`/`Fx65` treat the index register after a bulk load/store.
+enum LoadStoreQuirk {
+    Unchanged,
+    IncrementByX,
+    IncrementByXPlus1,
+}
+
+// Per-implementation behavioural quirks. Different CHIP-8 families disagree on
+// several ambiguous opcodes; this selects which convention the interpreter
+// follows. Defaults to the classic COSMAC VIP behaviour.
+struct Quirks {
+    // `8xy6`/`8xyE`: true sets Vx = Vy before shifting (VIP); false shifts Vx
+    // in place, ignoring Vy (SUPER-CHIP).
+    shift_uses_vy: bool,
+    // `Fx55`/`Fx65`: how the index register moves after the transfer.
+    load_store: LoadStoreQuirk,
+    // `Dxyn`: true wraps sprites around the screen edges, false clips them.
+    wrap_sprites: bool,
+    // `Bnnn`: false adds V0 (VIP); true adds Vx where x is the high nibble
+    // (SUPER-CHIP's `Bxnn`).
+    jump_uses_vx: bool,
+}
+
+impl Quirks {
+    fn cosmac() -> Quirks {
+        Quirks {
+            shift_uses_vy: true,
+            load_store: LoadStoreQuirk::IncrementByXPlus1,
+            wrap_sprites: false,
+            jump_uses_vx: false,
+        }
+    }
+
+    fn chip48() -> Quirks {
+        Quirks {
+            shift_uses_vy: false,
+            load_store: LoadStoreQuirk::IncrementByX,
+            wrap_sprites: false,
+            jump_uses_vx: true,
+        }
+    }
+
+    fn schip() -> Quirks {
+        Quirks {
+            shift_uses_vy: false,
+            load_store: LoadStoreQuirk::Unchanged,
+            wrap_sprites: false,
+            jump_uses_vx: true,
+        }
+    }
+}
+
+// Fixed-size circular history of the last `PC_HISTORY_SIZE` executed
+// `(pc, opcode)` pairs. Overwrites the oldest entry once full and is dumped,
+// newest first, by `Chip8::crash` so the execution trail is visible.
+struct RingBuffer {
+    entries: [(usize, u16); PC_HISTORY_SIZE],
+    head: usize,
+    len: usize,
+}
+
+impl RingBuffer {
+    fn new() -> RingBuffer {
+        RingBuffer {
+            entries: [(0, 0); PC_HISTORY_SIZE],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, pc: usize, opcode: u16) {
+        self.entries[self.head] = (pc, opcode);
+        self.head = (self.head + 1) % PC_HISTORY_SIZE;
+        if self.len < PC_HISTORY_SIZE {
+            self.len += 1;
+        }
+    }
+
+    fn dump(&self) {
+        eprintln!("---- execution trace (newest first) ----");
+        for i in 0..self.len {
+            let idx = (self.head + PC_HISTORY_SIZE - 1 - i) % PC_HISTORY_SIZE;
+            let (pc, opcode) = self.entries[idx];
+            eprintln!("{:#05x}: {:#06x}  {}", pc, opcode, disassemble(opcode));
+        }
+    }
+}
+
+// Interactive debugging state threaded through the interpreter. When enabled
+// the CPU pauses into a small REPL at breakpoints, on single steps, or when an
+// invalid opcode is decoded, instead of dumping a line per instruction.
+struct Debugger {
+    enabled: bool,
+    breakpoints: HashSet<usize>,
+    trace_only: bool,
+    run_forever: bool,
+    steps_remaining: u64,
+}
+
+impl Debugger {
+    fn new() -> Debugger {
+        Debugger {
+            enabled: false,
+            breakpoints: HashSet::new(),
+            trace_only: false,
+            run_forever: true,
+            steps_remaining: 0,
+        }
+    }
 }
 
 struct Chip8 {
@@ -52,14 +246,17 @@ struct Chip8 {
     pc: usize,
     old_pc: usize,
     index_register: u16, // actually 12 bits
-    delay_timer: u8,
-    sound_timer: u8,
+    delay_timer: Timer,
+    sound_timer: Timer,
     gfx: [u8; PIXEL_WIDTH * PIXEL_HEIGHT],
     draw_flag: bool,
 	keys: u32,
     sp: usize,
     stack: [usize; STACK_SIZE],
-    sound_iterator: u32,
+    debugger: Debugger,
+    save_dir: PathBuf,
+    pc_history: RingBuffer,
+    quirks: Quirks,
 }
 
 impl Chip8 {
@@ -76,40 +273,184 @@ impl Chip8 {
             pc: 0x200,
             old_pc: 0x200,
             index_register: 0x0000,
-            delay_timer: 0x0,
-            sound_timer: 0x0,
+            delay_timer: Timer::new(),
+            sound_timer: Timer::new(),
             gfx: [0; PIXEL_WIDTH * PIXEL_HEIGHT],
             draw_flag: false,
 			keys: 0x00000000,
             sp: 0,
             stack: [0; STACK_SIZE],
-            sound_iterator: 0,
+            debugger: Debugger::new(),
+            save_dir: PathBuf::new(),
+            pc_history: RingBuffer::new(),
+            quirks: Quirks::cosmac(),
         }
     }
 
-    fn load_rom(&mut self, rom_name: String) -> io::Result<()> {
+    fn load_rom(&mut self, rom_name: String) -> io::Result<usize> {
+        // Save states for this ROM live in their own directory keyed off the
+        // ROM's file name, e.g. `saves/pong.ch8/`.
+        let stem = std::path::Path::new(&rom_name)
+            .file_name()
+            .unwrap_or_else(|| std::ffi::OsStr::new("rom"));
+        self.save_dir = PathBuf::from("saves").join(stem);
+
         let mut f = File::open(rom_name)?;
-        f.read(&mut self.memory[0x200 ..]);
-        Ok(()) 
+        let mut rom = Vec::new();
+        f.read_to_end(&mut rom)?;
+
+        let capacity = 0x1000 - 0x200;
+        if rom.len() > capacity {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "ROM is {} bytes, which exceeds the {} bytes of program space",
+                    rom.len(),
+                    capacity
+                ),
+            ));
+        }
+
+        self.memory[0x200..0x200 + rom.len()].copy_from_slice(&rom);
+        Ok(rom.len())
+    }
+
+    // Serialize the live machine state into the save-state byte layout. The
+    // debugger, draw flag and save directory are intentionally left out so a
+    // state restores the program, not the host's inspection session.
+    fn serialize(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&self.memory);
+        buf.extend_from_slice(&self.registers);
+        buf.extend_from_slice(&self.index_register.to_be_bytes());
+        buf.extend_from_slice(&(self.pc as u16).to_be_bytes());
+        buf.push(self.sp as u8);
+        for slot in &self.stack {
+            buf.extend_from_slice(&(*slot as u16).to_be_bytes());
+        }
+        buf.push(self.delay_timer.get());
+        buf.push(self.sound_timer.get());
+        buf.extend_from_slice(&self.gfx);
+        buf.extend_from_slice(&self.keys.to_be_bytes());
+        buf
+    }
+
+    // Number of bytes a well formed save state occupies.
+    fn state_len() -> usize {
+        0x1000 + 16 + 2 + 2 + 1 + STACK_SIZE * 2 + 1 + 1 + PIXEL_WIDTH * PIXEL_HEIGHT + 4
+    }
+
+    // Restore machine state from a previously serialized buffer, rejecting
+    // anything that is not exactly the expected size.
+    fn deserialize(&mut self, data: &[u8]) -> io::Result<()> {
+        if data.len() != Chip8::state_len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "corrupt or incompatible save state",
+            ));
+        }
+        let mut c = 0;
+        self.memory.copy_from_slice(&data[c..c + 0x1000]);
+        c += 0x1000;
+        self.registers.copy_from_slice(&data[c..c + 16]);
+        c += 16;
+        self.index_register = u16::from_be_bytes([data[c], data[c + 1]]);
+        c += 2;
+        self.pc = u16::from_be_bytes([data[c], data[c + 1]]) as usize;
+        c += 2;
+        self.sp = data[c] as usize;
+        c += 1;
+        for slot in self.stack.iter_mut() {
+            *slot = u16::from_be_bytes([data[c], data[c + 1]]) as usize;
+            c += 2;
+        }
+        self.delay_timer.set(data[c]);
+        c += 1;
+        self.sound_timer.set(data[c]);
+        c += 1;
+        self.gfx.copy_from_slice(&data[c..c + PIXEL_WIDTH * PIXEL_HEIGHT]);
+        c += PIXEL_WIDTH * PIXEL_HEIGHT;
+        self.keys = u32::from_be_bytes([data[c], data[c + 1], data[c + 2], data[c + 3]]);
+        self.draw_flag = true;
+        Ok(())
+    }
+
+    // Freeze the whole machine into `saves/<rom>/slot<N>.sav`.
+    fn save_state(&self, slot: usize) -> io::Result<PathBuf> {
+        fs::create_dir_all(&self.save_dir)?;
+        let path = self.save_dir.join(format!("slot{}.sav", slot));
+        fs::write(&path, self.serialize())?;
+        Ok(path)
+    }
+
+    // Restore a frozen machine. With an explicit slot the matching file is
+    // loaded; without one ("load latest") the newest state is chosen by file
+    // modification time rather than by name. Returns the path restored from,
+    // or `None` when no matching state exists.
+    fn load_state(&mut self, slot: Option<usize>) -> io::Result<Option<PathBuf>> {
+        let path = match slot {
+            Some(s) => self.save_dir.join(format!("slot{}.sav", s)),
+            None => match self.latest_state_path() {
+                Some(p) => p,
+                None => return Ok(None),
+            },
+        };
+        if !path.exists() {
+            return Ok(None);
+        }
+        let data = fs::read(&path)?;
+        self.deserialize(&data)?;
+        Ok(Some(path))
+    }
+
+    // Pick the most recently written `.sav` in the ROM's save directory by
+    // inspecting modification time, so "load latest" ignores slot naming.
+    fn latest_state_path(&self) -> Option<PathBuf> {
+        let mut newest: Option<(std::time::SystemTime, PathBuf)> = None;
+        for entry in fs::read_dir(&self.save_dir).ok()?.flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("sav") {
+                continue;
+            }
+            if let Ok(modified) = fs::metadata(&path).and_then(|m| m.modified()) {
+                if newest.as_ref().map_or(true, |(t, _)| modified > *t) {
+                    newest = Some((modified, path));
+                }
+            }
+        }
+        newest.map(|(_, p)| p)
     }
 
     fn fetch_opcode(&mut self) -> io::Result<u16> {
+        if self.pc + 1 >= self.memory.len() {
+            self.crash(format!("Program counter {:#05x} is out of memory range", self.pc));
+        }
         let first_byte = self.memory[self.pc];
         let second_byte = self.memory[self.pc + 1];
         let opcode = ((first_byte as u16) << 8) | (second_byte as u16);
         self.pc += 2;
-        if self.pc > 0xFFE {
-            panic!("Program counter is out of memory range");
-        }
         Ok(opcode)
     }
 
+    // Dump the execution history and abort. Used for the faults the
+    // interpreter checks for (an invalid opcode with the debugger off, PC out
+    // of range, stack underflow/overflow) so the user sees how execution
+    // reached the faulting instruction. Other panics, such as an out of
+    // bounds memory access, do not dump the history.
+    fn crash(&self, msg: String) -> ! {
+        self.pc_history.dump();
+        panic!("{}", msg);
+    }
+
     fn op_00E0(&mut self) {
         self.gfx.iter_mut().for_each(|m| *m = 0);
         self.draw_flag = true;
     }
 
     fn op_00EE(&mut self) {
+        if self.sp == 0 {
+            self.crash("Stack underflow on RET (00EE)".to_string());
+        }
         self.sp -= 1;
         self.pc = self.stack[self.sp];
     }
@@ -119,6 +460,9 @@ impl Chip8 {
     }
 
     fn op_2nnn(&mut self, nnn: usize) {
+        if self.sp >= STACK_SIZE {
+            self.crash("Stack overflow on CALL (2nnn)".to_string());
+        }
         self.stack[self.sp] = self.pc;
         self.sp += 1;
         self.pc = nnn;
@@ -183,6 +527,9 @@ impl Chip8 {
     }
 
     fn op_8xy6(&mut self, x: usize, y: usize) {
+        if self.quirks.shift_uses_vy {
+            self.registers[x] = self.registers[y];
+        }
         let lsb = self.registers[x] & 0x1;
         self.registers[0xF] = lsb;
         self.registers[x] = self.registers[x] >> 1;
@@ -192,6 +539,9 @@ impl Chip8 {
     }
 
     fn op_8xyE(&mut self, x: usize, y: usize) {
+        if self.quirks.shift_uses_vy {
+            self.registers[x] = self.registers[y];
+        }
         let msb = self.registers[x] >> 7 & 0x1;
         self.registers[0xF] = msb;
         self.registers[x] = self.registers[x] << 1;
@@ -208,7 +558,11 @@ impl Chip8 {
     }
 
     fn op_Bnnn(&mut self, nnn: usize) {
-        let offset = self.registers[0x00] as usize;
+        let offset = if self.quirks.jump_uses_vx {
+            self.registers[(nnn >> 8) & 0xF] as usize
+        } else {
+            self.registers[0x00] as usize
+        };
         self.pc = nnn + offset;
     }
 
@@ -220,15 +574,22 @@ impl Chip8 {
     fn op_Dxyn(&mut self, x: usize, y: usize, n: usize) {
         self.draw_flag = true;
         self.registers[0xF] = 0;
-        let x_coord = self.registers[x] as usize;
-        let y_coord = self.registers[y] as usize;
-        let offset = self.index_register as usize;
+        // The origin is always masked to the screen regardless of the
+        // wrap/clip quirk; only the sprite body past the edge is affected.
+        let x_coord = self.registers[x] as usize % PIXEL_WIDTH;
+        let y_coord = self.registers[y] as usize % PIXEL_HEIGHT;
+        let base = self.index_register as usize;
         for i in 0..n {
-            let pixel = self.memory[offset + i];
-            for x in 0..8 {
-                if pixel & (0x80 >> x) != 0 {
-                    let offset = (x_coord + x)+(PIXEL_WIDTH*(y_coord + i));
-                    if offset < PIXEL_WIDTH * PIXEL_HEIGHT {
+            let pixel = self.memory[base + i];
+            for col in 0..8 {
+                if pixel & (0x80 >> col) != 0 {
+                    let (px, py) = if self.quirks.wrap_sprites {
+                        ((x_coord + col) % PIXEL_WIDTH, (y_coord + i) % PIXEL_HEIGHT)
+                    } else {
+                        (x_coord + col, y_coord + i)
+                    };
+                    if px < PIXEL_WIDTH && py < PIXEL_HEIGHT {
+                        let offset = px + PIXEL_WIDTH * py;
                         if self.gfx[offset] ^ 1 == 0 {
                             self.registers[0xF] = 1;
                         }
@@ -254,7 +615,7 @@ impl Chip8 {
     }
 
     fn op_Fx07(&mut self, x: usize) {
-        self.registers[x] = self.delay_timer;
+        self.registers[x] = self.delay_timer.get();
     }
 
     fn op_Fx0A(&mut self, x: usize) {
@@ -270,11 +631,11 @@ impl Chip8 {
     }
 
     fn op_Fx15(&mut self, x: usize) {
-        self.delay_timer = self.registers[x];
+        self.delay_timer.set(self.registers[x]);
     }
 
     fn op_Fx18(&mut self, x: usize) {
-        self.sound_timer = self.registers[x];
+        self.sound_timer.set(self.registers[x]);
     }
 
     fn op_Fx1E(&mut self, x: usize) {
@@ -296,19 +657,26 @@ impl Chip8 {
         for i in 0..(x + 1) {
             self.memory[(self.index_register as usize) + i] = self.registers[i];
         }
-        self.index_register = self.index_register + (x as u16) + 1;
+        self.advance_index(x);
     }
 
     fn op_Fx65(&mut self, x: usize) {
         for i in 0..(x + 1) {
             self.registers[i] = self.memory[(self.index_register as usize) + i];
         }
-        self.index_register = self.index_register + (x as u16) + 1;
+        self.advance_index(x);
+    }
+
+    // Move the index register after a bulk load/store per the active quirk.
+    fn advance_index(&mut self, x: usize) {
+        match self.quirks.load_store {
+            LoadStoreQuirk::Unchanged => {}
+            LoadStoreQuirk::IncrementByX => self.index_register += x as u16,
+            LoadStoreQuirk::IncrementByXPlus1 => self.index_register += (x as u16) + 1,
+        }
     }
 
     fn execute_opcode(&mut self, opcode: u16) {
-        println!("pc: {:#04x}, opcode: {:#04x}", self.pc-2, opcode);
-        self.print_registers();
         let x_reg = ((opcode >> 8) & 0x000F) as usize;
         let y_reg = ((opcode >> 4) & 0x000F) as usize;
         let nnn = (opcode & 0x0FFF) as usize;
@@ -327,7 +695,7 @@ impl Chip8 {
             0x5000..0x5FFF => {
                 match nibble_instruction {
                     0x0 => self.op_5xy0(x_reg, y_reg),
-                    _ => panic!("Invalid opcode {:#04x}", opcode),
+                    _ => self.on_invalid(opcode),
                 };
             },
             0x6000..0x6FFF => self.op_6xkk(x_reg, kk),
@@ -343,13 +711,13 @@ impl Chip8 {
                     0x6 => self.op_8xy6(x_reg, y_reg),
                     0x7 => self.op_8xy7(x_reg, y_reg),
                     0xE => self.op_8xyE(x_reg, y_reg),
-                    _ => panic!("Invalid opcode {:#04x}", opcode),
+                    _ => self.on_invalid(opcode),
                 };
             },
             0x9000..0x9FFF => {
                 match nibble_instruction {
                     0x0 => self.op_9xy0(x_reg, y_reg),
-                    _ => panic!("Invalid opcode {:#04x}", opcode),
+                    _ => self.on_invalid(opcode),
                 };
             },
             0xA000..0xAFFF => self.op_Annn(nnn),
@@ -360,7 +728,7 @@ impl Chip8 {
                 match byte_instruction {
                     0x9E => self.op_Ex9E(x_reg),
                     0xA1 => self.op_ExA1(x_reg),
-                    _ => panic!(format!("Invalid opcode {:#04x}", opcode)),
+                    _ => self.on_invalid(opcode),
                 }
             },
             0xF000..0xFFFF => {
@@ -374,33 +742,172 @@ impl Chip8 {
                     0x33 => self.op_Fx33(x_reg),
                     0x55 => self.op_Fx55(x_reg),
                     0x65 => self.op_Fx65(x_reg),
-                    _ => panic!(format!("Invalid opcode {:#04x}", opcode)),
+                    _ => self.on_invalid(opcode),
                 }
             }
             
-            _ => panic!(format!("Invalid opcode {:#04x}", opcode)),
+            _ => self.on_invalid(opcode),
         }
 
     }
 
     fn step(&mut self) {
+        self.debug_check();
         let opcode = self.fetch_opcode().unwrap();
+        self.pc_history.push(self.pc - 2, opcode);
+        if self.debugger.trace_only {
+            println!("{:#05x}: {}", self.pc - 2, disassemble(opcode));
+        }
+        self.execute_opcode(opcode);
+    }
 
-        if self.sound_iterator % 16 == 0 {
-            if self.sound_timer > 0 {
-                self.sound_timer -= 1;
-            }
+    // Called on an invalid opcode. Drops into the debugger if it is enabled so
+    // the fault can be inspected; otherwise the old panic behaviour stands.
+    fn on_invalid(&mut self, opcode: u16) {
+        if self.debugger.enabled {
+            eprintln!("Invalid opcode {:#06x} at {:#05x}", opcode, self.pc.wrapping_sub(2));
+            self.debugger.run_forever = false;
+            self.debugger.steps_remaining = 0;
+            self.debug_repl();
+        } else {
+            self.crash(format!("Invalid opcode {:#06x}", opcode));
+        }
+    }
 
-            if self.delay_timer > 0 {
-                self.delay_timer -= 1;
+    // Consulted before every fetch. Pauses into the REPL when the upcoming PC
+    // is a breakpoint, or once a pending single-step budget is exhausted.
+    fn debug_check(&mut self) {
+        if !self.debugger.enabled {
+            return;
+        }
+        if self.debugger.breakpoints.contains(&self.pc) {
+            println!("Breakpoint hit at {:#05x}", self.pc);
+            self.debugger.run_forever = false;
+            self.debugger.steps_remaining = 0;
+        }
+        if self.debugger.run_forever {
+            return;
+        }
+        if self.debugger.steps_remaining > 0 {
+            self.debugger.steps_remaining -= 1;
+            return;
+        }
+        self.debug_repl();
+    }
+
+    // The interactive prompt. Blocks reading commands until one resumes
+    // execution (`continue`, `step`, or an empty line, which steps once) or
+    // `quit` exits. The other commands inspect state or edit breakpoints and
+    // tracing, then loop back for another command.
+    fn debug_repl(&mut self) {
+        loop {
+            print!("(chip8dbg) ");
+            stdout().flush().unwrap();
+            let mut line = String::new();
+            if stdin().read_line(&mut line).unwrap() == 0 {
+                // EOF on stdin: resume and let the program run to completion.
+                self.debugger.run_forever = true;
+                return;
             }
+            let mut parts = line.split_whitespace();
+            let cmd = match parts.next() {
+                Some(c) => c,
+                None => {
+                    self.debugger.steps_remaining = 0;
+                    return;
+                }
+            };
+            let parse_addr = |s: Option<&str>| -> Option<usize> {
+                s.and_then(|s| usize::from_str_radix(s.trim_start_matches("0x"), 16).ok())
+            };
+            match cmd {
+                "break" | "b" => match parse_addr(parts.next()) {
+                    Some(addr) => {
+                        self.debugger.breakpoints.insert(addr);
+                        println!("Breakpoint set at {:#05x}", addr);
+                    }
+                    None => println!("usage: break <addr>"),
+                },
+                "delete" | "d" => match parse_addr(parts.next()) {
+                    Some(addr) => {
+                        if self.debugger.breakpoints.remove(&addr) {
+                            println!("Breakpoint removed at {:#05x}", addr);
+                        } else {
+                            println!("No breakpoint at {:#05x}", addr);
+                        }
+                    }
+                    None => println!("usage: delete <addr>"),
+                },
+                "step" | "s" => {
+                    let n: u64 = parts.next().and_then(|s| s.parse().ok()).unwrap_or(1);
+                    self.debugger.steps_remaining = n.saturating_sub(1);
+                    self.debugger.run_forever = false;
+                    return;
+                }
+                "continue" | "c" => {
+                    self.debugger.run_forever = true;
+                    return;
+                }
+                "regs" | "r" => self.print_registers(),
+                "mem" | "m" => {
+                    match (parse_addr(parts.next()), parse_addr(parts.next())) {
+                        (Some(addr), Some(len)) => self.dump_memory(addr, len),
+                        _ => println!("usage: mem <addr> <len>"),
+                    }
+                }
+                "disasm" => match parse_addr(parts.next()) {
+                    Some(addr) => {
+                        let n = parts.next().and_then(|s| s.parse().ok()).unwrap_or(8);
+                        self.dump_disasm(addr, n);
+                    }
+                    None => println!("usage: disasm <addr> [n]"),
+                },
+                "trace" => {
+                    self.debugger.trace_only = !self.debugger.trace_only;
+                    println!("trace_only = {}", self.debugger.trace_only);
+                }
+                "quit" | "q" => std::process::exit(0),
+                "help" | "h" => {
+                    println!("break <addr>  delete <addr>  step [n]  continue");
+                    println!("regs  mem <addr> <len>  disasm <addr> [n]  trace  quit");
+                }
+                _ => println!("unknown command '{}', try 'help'", cmd),
+            }
+        }
+    }
 
-            self.sound_iterator = 0;
+    // Hex dump `len` bytes of memory starting at `addr`, 16 bytes per row.
+    fn dump_memory(&self, addr: usize, len: usize) {
+        let end = (addr + len).min(self.memory.len());
+        let mut offset = addr;
+        while offset < end {
+            print!("{:#05x}: ", offset);
+            for byte in &self.memory[offset..(offset + 0x10).min(end)] {
+                print!("{:02x} ", byte);
+            }
+            println!();
+            offset += 0x10;
         }
+    }
 
-        self.sound_iterator = self.sound_iterator.wrapping_add(1);
+    // Decode and print `n` successive opcodes starting at `addr`.
+    fn dump_disasm(&self, addr: usize, n: usize) {
+        let mut offset = addr;
+        for _ in 0..n {
+            if offset + 1 >= self.memory.len() {
+                break;
+            }
+            let opcode = ((self.memory[offset] as u16) << 8) | (self.memory[offset + 1] as u16);
+            println!("{:#05x}: {}", offset, disassemble(opcode));
+            offset += 2;
+        }
+    }
 
-        self.execute_opcode(opcode);
+    // Tick both timers down one step. Called once per 1/60 s frame from the
+    // main loop, independently of the interpreter clock.
+    fn tick_timers(&mut self) {
+        self.delay_timer.tick();
+        self.sound_timer.tick();
     }
 
 	fn set_key(&mut self, key: u8) {
@@ -412,19 +919,6 @@ impl Chip8 {
         self.keys = 0x0000;
 	}	
 
-    fn print_memory(&self) {
-        let mut x: u32 = 0;
-        for byte in &self.memory {
-            if x % 0x10 == 0 {
-                println!("");
-                print!("{:#03x}: ", x);
-            }
-            print!("{:#03x} ", byte);
-            x += 1;
-        }
-        println!("");
-    }
-
     fn print_registers(&self) {
         let mut x: u32 = 0;
         for register in &self.registers {
@@ -432,8 +926,10 @@ impl Chip8 {
             x += 1;
         }
         println!("I: {:#02x}", self.index_register);
-        println!("Delay Timer: {:#02x}", self.delay_timer);
-        println!("Sound Timer: {:#02x}", self.sound_timer);
+        println!("PC: {:#05x}", self.pc);
+        println!("SP: {:#02x}", self.sp);
+        println!("Delay Timer: {:#02x}", self.delay_timer.get());
+        println!("Sound Timer: {:#02x}", self.sound_timer.get());
     }
 }
 
@@ -449,9 +945,62 @@ fn update_graphics(chip8: &mut Chip8, display_buf: &mut Vec<u32>) {
     }
 }
 
+// Command line configuration. A bare argument is the ROM path (if several are
+// given, the last one is used); the flags tune the interpreter.
+struct Config {
+    rom_name: String,
+    ips: u32,
+    debug: bool,
+    quirks: Quirks,
+}
+
+// Pull the ROM path and any flags off the command line. `--clock`/`--ipf N`
+// overrides the interpreter speed in instructions per second, and `--debug`
+// starts paused in the interactive debugger.
+fn parse_args() -> Config {
+    let mut rom_name = None;
+    let mut ips = DEFAULT_INSTRUCTIONS_PER_SECOND;
+    let mut debug = false;
+    let mut quirks = Quirks::cosmac();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--clock" | "--ipf" => {
+                ips = args
+                    .next()
+                    .and_then(|v| v.parse().ok())
+                    .expect("--clock/--ipf expects an instructions-per-second value");
+            }
+            "--debug" => debug = true,
+            "--quirks" => {
+                quirks = match args.next().as_deref() {
+                    Some("cosmac") => Quirks::cosmac(),
+                    Some("chip48") => Quirks::chip48(),
+                    Some("schip") => Quirks::schip(),
+                    _ => panic!("--quirks expects a profile: cosmac, chip48 or schip"),
+                };
+            }
+            _ => rom_name = Some(arg),
+        }
+    }
+    Config {
+        rom_name: rom_name.expect("Missing argument"),
+        ips,
+        debug,
+        quirks,
+    }
+}
+
 fn main() {
-    let rom_name = env::args().nth(1).expect("Missing argument");
+    let config = parse_args();
+    let instructions_per_frame = (config.ips / TIMER_HZ).max(1);
+    let frame_duration = Duration::from_secs_f64(1.0 / TIMER_HZ as f64);
     let mut chip8 = Chip8::new();
+    chip8.quirks = config.quirks;
+    if config.debug {
+        chip8.debugger.enabled = true;
+        chip8.debugger.run_forever = false;
+    }
     let mut display_buf: Vec<u32> = vec![0; PIXEL_WIDTH * PIXEL_HEIGHT];
     let mut options = WindowOptions::default();
     options.scale = minifb::Scale::X16;
@@ -474,9 +1023,11 @@ fn main() {
 
     window.limit_update_rate(Some(std::time::Duration::from_millis(1)));
 
-    chip8.load_rom(rom_name).unwrap();
+    let rom_len = chip8.load_rom(config.rom_name).unwrap();
+    println!("Loaded {} bytes of ROM", rom_len);
 
-    chip8.print_memory();
+    let mut last = Instant::now();
+    let mut accumulator = Duration::from_secs(0);
 
     while window.is_open() && !window.is_key_down(Key::Escape) {
 		window.get_keys().map(|keys| {
@@ -503,13 +1054,43 @@ fn main() {
 			}
 		});
 
-        chip8.step();
+        // Quicksave / quickload hotkeys. F5 writes slot 0; F9 restores the
+        // most recently written state for this ROM.
+        if window.is_key_pressed(Key::F5, KeyRepeat::No) {
+            match chip8.save_state(0) {
+                Ok(path) => println!("Saved state to {}", path.display()),
+                Err(e) => eprintln!("Save failed: {}", e),
+            }
+        }
+        if window.is_key_pressed(Key::F9, KeyRepeat::No) {
+            match chip8.load_state(None) {
+                Ok(Some(path)) => println!("Loaded state from {}", path.display()),
+                Ok(None) => eprintln!("No save state found"),
+                Err(e) => eprintln!("Load failed: {}", e),
+            }
+        }
+
+        let now = Instant::now();
+        accumulator += now - last;
+        last = now;
+
+        // Advance the machine one 60 Hz frame at a time: run a fixed batch of
+        // CPU instructions, then tick the timers exactly once per frame so
+        // game speed no longer depends on host loop overhead.
+        while accumulator >= frame_duration {
+            accumulator -= frame_duration;
+            for _ in 0..instructions_per_frame {
+                chip8.step();
+            }
+            chip8.tick_timers();
+        }
+
         if chip8.draw_flag {
             chip8.draw_flag = false;
             update_graphics(&mut chip8, &mut display_buf);
         }
 
-        if chip8.sound_timer > 0 { sink.play() } else { sink.pause() }
+        if chip8.sound_timer.get() > 0 { sink.play() } else { sink.pause() }
 
         window
             .update_with_buffer(&display_buf, PIXEL_WIDTH, PIXEL_HEIGHT)